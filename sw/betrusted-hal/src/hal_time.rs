@@ -124,6 +124,9 @@ impl PartialOrd for TimeMs {
     }
 }
 
+/// Busy-wait variant: spins the CPU re-reading `TIME0` until `ms` have elapsed.
+/// Kept around for contexts that run before `mark_irq_ready()` has unlocked the
+/// ticktimer interrupt; once that's done, prefer `sleep_ms`.
 pub fn delay_ms(ms: u32) {
     let stop_time = TimeMs::now().add_ms(ms);
     loop {
@@ -133,6 +136,88 @@ pub fn delay_ms(ms: u32) {
     }
 }
 
+/// How far out `ticktimer_int_handler` re-arms the alarm after every fire to
+/// keep petting the watchdog (see the caller's own handler). `sleep_until`
+/// uses the same constant to cap how far out *it* ever arms the alarm, so a
+/// long sleep can't leave the watchdog starved until the sleep finishes --
+/// see `sleep_until`'s doc comment.
+pub const WATCHDOG_PET_MS: u32 = 50;
+
+/// Set once the caller has told us the ticktimer alarm interrupt is claimed
+/// and unmasked (see `mark_irq_ready`). `sleep_ms`/`sleep_until` fall back to
+/// busy-waiting if this hasn't happened yet, so they're safe to call from
+/// early boot code too.
+static mut IRQ_READY: bool = false;
+
+/// Tells `sleep_ms`/`sleep_until` they can `wfi` instead of spinning.
+///
+/// There's only one ticktimer alarm IRQ on this board, and on this target
+/// `sys_interrupt_claim` replaces whatever handler previously owned an IRQ
+/// rather than chaining to it -- so this module deliberately does *not* claim
+/// the interrupt itself, to avoid silently displacing a handler the caller
+/// already installed (e.g. one that also feeds a watchdog on the same
+/// alarm). The caller must claim `TICKTIMER_IRQ` and enable `EV_ENABLE_ALARM`
+/// itself first, with a handler that clears `EV_PENDING_ALARM` on every fire
+/// -- that's all `sleep_until`'s wfi-wake loop needs to make progress -- then
+/// call this once to unlock the interrupt-driven path.
+pub fn mark_irq_ready() {
+    unsafe {
+        IRQ_READY = true;
+    }
+}
+
+/// Sleep until the 40-bit hardware timer reaches `target`, using `wfi` instead
+/// of busy-waiting. Returns immediately without arming anything if `target` is
+/// already in the past. Crossing the 32-bit low-word boundary is handled by
+/// `TimeMs` itself (the same `add_ms`/`PartialOrd` logic used everywhere else),
+/// so there's no special-casing needed here.
+///
+/// There's only one MSLEEP_TARGET register, and the caller's alarm handler
+/// also uses it to re-arm its own watchdog-petting wakeup on every fire (see
+/// `WATCHDOG_PET_MS`). Arming `target` directly would starve that petting for
+/// any sleep longer than the petting interval -- the alarm simply wouldn't
+/// fire again until `target`, however far out that is. So instead this arms
+/// in `WATCHDOG_PET_MS`-sized (or smaller) steps and re-checks progress after
+/// each wakeup, never leaving the alarm further out than the next pet is due.
+///
+/// Falls back to a busy-wait if `mark_irq_ready()` hasn't run yet.
+pub fn sleep_until(target: TimeMs) {
+    if TimeMs::now() >= target {
+        return;
+    }
+    if unsafe { !IRQ_READY } {
+        loop {
+            if TimeMs::now() >= target {
+                break;
+            }
+        }
+        return;
+    }
+
+    let mut ticktimer_csr = CSR::new(HW_TICKTIMER_BASE as *mut u32);
+
+    loop {
+        let now = TimeMs::now();
+        if now >= target {
+            break;
+        }
+        let pet_deadline = now.add_ms(WATCHDOG_PET_MS);
+        // TimeMs only implements PartialOrd (it's not Ord), so pick the
+        // earlier of the two by hand rather than via core::cmp::min.
+        let next = if pet_deadline < target { pet_deadline } else { target };
+        ticktimer_csr.wo(utra::ticktimer::MSLEEP_TARGET1, next.time1);
+        ticktimer_csr.wo(utra::ticktimer::MSLEEP_TARGET0, next.time0);
+        riscv::asm::wfi();
+        // Either `next` fired, a spurious wakeup happened, or an unrelated
+        // IRQ did -- loop back around and re-check/re-arm either way.
+    }
+}
+
+/// Sleep for `ms` milliseconds via the interrupt-driven path. See `sleep_until`.
+pub fn sleep_ms(ms: u32) {
+    sleep_until(TimeMs::now().add_ms(ms));
+}
+
 /// Return the low word from the 40-bit hardware millisecond timer.
 pub fn get_time_ms() -> u32 {
     let ticktimer_csr = CSR::new(HW_TICKTIMER_BASE as *mut u32);
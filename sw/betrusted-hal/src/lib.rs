@@ -4,6 +4,7 @@ extern crate bitflags;
 extern crate volatile;
 extern crate utralib;
 extern crate riscv;
+extern crate xous_nommu;
 
 pub mod hal_time;
 pub mod mem_locs;
@@ -68,35 +68,33 @@ impl Adc {
     }
     /// given an ADC channel, return the delta of the reading versus the calibration
     pub fn read(&mut self, channel: utralib::Field) -> Option<u16> {
-        // convert the GPIO field selector into an ADC channel number
-        let mut ch = 16;
-        for (index, &field) in CHANNEL_MAP.iter().enumerate() {
-            if field == channel {
-                ch = index;
-                break;
-            }
-        }
-        // the channel map field was invalid
-        if ch == 16 {
-            return None
-        }
+        let ch = channel_for(channel)?;
+        self.read_cross(channel, ch as u32)
+    }
 
+    /// Drives `drive`'s mux enable and reads back ADC channel `sense_ch`.
+    /// When `sense_ch` is `drive`'s own channel (what `read` does) this is a
+    /// normal connectivity check; when it's a *different* pin's channel, a
+    /// low delta means current coupled onto a node nothing was driving it
+    /// through -- i.e. `drive` is shorted to whatever pin `sense_ch` belongs
+    /// to.
+    pub fn read_cross(&mut self, drive: utralib::Field, sense_ch: u32) -> Option<u16> {
         // get the ibus cal value; no measurement values should be muxed at this point
         // set the mux to 0
         self.dut.wo(utra::dut::DUT, 0);
         delay_ms(2);
         let cal = self.read_inner(3 + 8);
 
-        // mux in the DUT measurement channel
-        self.dut.wfo(channel, 1);
+        // mux in the DUT drive channel
+        self.dut.wfo(drive, 1);
         delay_ms(2);
-        let meas = self.read_inner(ch as u32);
+        let meas = self.read_inner(sense_ch);
         // set the mux to 0
         self.dut.wo(utra::dut::DUT, 0);
 
         loghexln!(LL::Trace, " cal: ", cal);
         loghexln!(LL::Trace, "meas: ", meas);
-        loghexln!(LL::Trace, "  ch: ", ch);
+        loghexln!(LL::Trace, "  ch: ", sense_ch);
 
         if meas >= cal {
             Some(0)
@@ -104,4 +102,9 @@ impl Adc {
             Some(cal - meas)
         }
     }
+}
+
+/// Maps a DUT mux enable field to its ADC channel number, per `CHANNEL_MAP`.
+pub fn channel_for(field: utralib::Field) -> Option<usize> {
+    CHANNEL_MAP.iter().position(|&f| f == field)
 }
\ No newline at end of file
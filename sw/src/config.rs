@@ -0,0 +1,149 @@
+//! Key=value configuration region, parsed once at boot so each burned unit can
+//! carry its own serial/mac and field-tunable pass/fail thresholds without
+//! rebuilding the firmware. Mirrors the `config.txt` scheme the artiq-zynq SD
+//! boot uses for `ip`/`mac`/`startup`. A key that's missing, or a region
+//! that's blank/erased (all `\0` or all `0xFF`), falls back to the compiled-in
+//! default below rather than failing the whole region.
+//!
+//! Region placement and size are xtask's call (see `create_image`'s region
+//! table, index 4); `CONFIG_LEN` here and `main`'s `CONFIG_BASE` just need to
+//! stay in sync with whatever offset it lands on.
+
+use core::str;
+
+pub const SERIAL_LEN: usize = 16;
+pub const MAC_LEN: usize = 17; // "xx:xx:xx:xx:xx:xx"
+pub const CONFIG_LEN: usize = 4096;
+/// Ed25519 signing seed, see `sign::Signer`.
+pub const SEED_LEN: usize = 32;
+
+const DEFAULT_NC_THRESH: u16 = 1000; // matches the old hard-coded MIN_NC_THRESH
+const DEFAULT_TIMEOUT_MS: u32 = 30_000;
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub serial: [u8; SERIAL_LEN],
+    pub serial_len: usize,
+    pub mac: [u8; MAC_LEN],
+    pub mac_len: usize,
+    /// ADC delta, in raw counts, above which a pin reads "not connected".
+    pub nc_thresh: u16,
+    pub timeout_ms: u32,
+    /// Per-unit Ed25519 signing seed, provisioned at manufacturing time (see
+    /// `sign::Signer`). All-zero means "not provisioned" -- every unit that
+    /// falls back to the default shares the same key, which `main` warns
+    /// about at boot.
+    pub seed: [u8; SEED_LEN],
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            serial: [0; SERIAL_LEN],
+            serial_len: 0,
+            mac: [0; MAC_LEN],
+            mac_len: 0,
+            nc_thresh: DEFAULT_NC_THRESH,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            seed: [0; SEED_LEN],
+        }
+    }
+}
+
+/// Parses one hex digit, case-insensitively.
+fn hex_nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a `2 * SEED_LEN`-character hex string into a seed. Returns `None`
+/// on any malformed input (wrong length, non-hex character) so the caller can
+/// fall back to the default rather than signing with a garbled key.
+fn parse_seed(value: &str) -> Option<[u8; SEED_LEN]> {
+    let bytes = value.as_bytes();
+    if bytes.len() != SEED_LEN * 2 {
+        return None;
+    }
+    let mut seed = [0u8; SEED_LEN];
+    for i in 0..SEED_LEN {
+        let hi = hex_nibble(bytes[2 * i])?;
+        let lo = hex_nibble(bytes[2 * i + 1])?;
+        seed[i] = (hi << 4) | lo;
+    }
+    Some(seed)
+}
+
+/// Parses a `key=value`-per-line config region into a `Config`, falling back
+/// to defaults for anything missing or unparseable.
+pub fn parse(region: &[u8]) -> Config {
+    let mut config = Config::default();
+    if region.iter().all(|&b| b == 0 || b == 0xFF) {
+        return config;
+    }
+    // Stop at the first NUL/erased byte so trailing blank flash doesn't get
+    // parsed as one giant empty "line".
+    let end = region.iter().position(|&b| b == 0 || b == 0xFF).unwrap_or(region.len());
+    let text = match str::from_utf8(&region[..end]) {
+        Ok(s) => s,
+        Err(_) => return config,
+    };
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let key = match parts.next() {
+            Some(k) => k.trim(),
+            None => continue,
+        };
+        let value = match parts.next() {
+            Some(v) => v.trim(),
+            None => continue,
+        };
+        match key {
+            "serial" => {
+                let n = value.len().min(SERIAL_LEN);
+                config.serial[..n].copy_from_slice(&value.as_bytes()[..n]);
+                config.serial_len = n;
+            }
+            "mac" => {
+                let n = value.len().min(MAC_LEN);
+                config.mac[..n].copy_from_slice(&value.as_bytes()[..n]);
+                config.mac_len = n;
+            }
+            "nc_thresh" => {
+                if let Ok(v) = value.parse::<u16>() {
+                    config.nc_thresh = v;
+                }
+            }
+            "timeout_ms" => {
+                if let Ok(v) = value.parse::<u32>() {
+                    config.timeout_ms = v;
+                }
+            }
+            "seed" => {
+                if let Some(v) = parse_seed(value) {
+                    config.seed = v;
+                }
+            }
+            _ => (), // unknown keys are ignored, not fatal
+        }
+    }
+    config
+}
+
+/// Reads and parses the config region out of the memory-mapped boot flash.
+///
+/// # Safety
+/// `base` must point at `CONFIG_LEN` bytes of readable memory-mapped flash
+/// (the config region xtask's `create_image` lays out blank and its
+/// `stamp-config` task fills in; `push_to_pi` does not stamp it).
+pub unsafe fn load(base: *const u8) -> Config {
+    let region = core::slice::from_raw_parts(base, CONFIG_LEN);
+    parse(region)
+}
@@ -12,8 +12,9 @@ use riscv_rt::entry;
 use utralib::generated::{
     utra, CSR, HW_CRG_BASE, HW_TICKTIMER_BASE, HW_DUT_BASE,
 };
+use betrusted_hal::hal_time;
 use betrusted_hal::hal_time::{
-    set_msleep_target_ticks, time_init, TimeMs, delay_ms,
+    set_msleep_target_ticks, time_init, TimeMs, sleep_ms, WATCHDOG_PET_MS,
 };
 use betrusted_hal::mem_locs::*;
 use core::fmt::Write;
@@ -25,6 +26,10 @@ mod uart;
 mod screen;
 mod sbled;
 mod adc;
+mod updater;
+mod config;
+mod sign;
+mod storage;
 
 // Configure Log Level (used in macro expansions)
 const LOG_LEVEL: LL = LL::Info;
@@ -32,13 +37,28 @@ const LOG_LEVEL: LL = LL::Info;
 // Constants
 const CONFIG_CLOCK_FREQUENCY: u32 = 18_000_000;
 
+// These mirror xtask's `create_image` region sizing exactly (HEADER_SIZE,
+// GATEWARE_REGION, the padded loader, and two KERNEL_REGION-sized kernel
+// slots) so CONFIG_BASE lands on the same offset xtask's region table (index
+// 4) and `stamp_config` actually wrote to. Update all of these together if
+// xtask's region layout ever changes.
+const HEADER_SIZE: u32 = 4096;
+const GATEWARE_REGION: u32 = 104 * 1024;
+const LOADER_REGION: u32 = 4096;
+const KERNEL_REGION: u32 = 76 * 1024;
+const CONFIG_OFFSET: u32 = HEADER_SIZE + GATEWARE_REGION + LOADER_REGION + 2 * KERNEL_REGION;
+const CONFIG_BASE: *const u8 = (spi::FLASH_MMAP_BASE + CONFIG_OFFSET) as *const u8;
+
 /// Infinite loop panic handler (TODO: fix this to use less power)
 #[panic_handler]
 fn panic(_panic: &PanicInfo<'_>) -> ! {
     loop {}
 }
 
-/// handles just the watchdog for now
+/// Handles just the watchdog for now. Fires on every ticktimer alarm,
+/// regardless of who armed it -- including `hal_time::sleep_until`, which
+/// deliberately never arms further out than `WATCHDOG_PET_MS` so this always
+/// gets to run and re-arm the alarm at least that often.
 fn ticktimer_int_handler(_irq_no: usize) {
     let mut ticktimer_csr = CSR::new(HW_TICKTIMER_BASE as *mut u32);
     let mut crg_csr = CSR::new(HW_CRG_BASE as *mut u32);
@@ -47,7 +67,7 @@ fn ticktimer_int_handler(_irq_no: usize) {
     crg_csr.wfo(utra::crg::WATCHDOG_RESET_CODE, 0x600d);
     crg_csr.wfo(utra::crg::WATCHDOG_RESET_CODE, 0xc0de);
 
-    set_msleep_target_ticks(50); // resetting this will also clear the alarm
+    set_msleep_target_ticks(WATCHDOG_PET_MS); // resetting this will also clear the alarm
 
     ticktimer_csr.wfo(utra::ticktimer::EV_PENDING_ALARM, 1);
 }
@@ -96,15 +116,21 @@ pub const LOWER_PINS: [(utralib::Field, &'static str); 12] = [
     (utra::dut::DUT_VBUS_B4, "VBUS: Pin B4"),
 ];
 
+/// `LOWER_PINS` then `UPPER_PINS`, matching `storage::Stats::fail_histogram`'s
+/// and `sign::Signer`'s report channel order (12 lower + 4 upper).
+pub const ALL_PINS: [(utralib::Field, &'static str); 16] = [
+    LOWER_PINS[0], LOWER_PINS[1], LOWER_PINS[2], LOWER_PINS[3],
+    LOWER_PINS[4], LOWER_PINS[5], LOWER_PINS[6], LOWER_PINS[7],
+    LOWER_PINS[8], LOWER_PINS[9], LOWER_PINS[10], LOWER_PINS[11],
+    UPPER_PINS[0], UPPER_PINS[1], UPPER_PINS[2], UPPER_PINS[3],
+];
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum PinBank {
     Upper,
     Lower
 }
 
-/// anything above this number is considered to be an "open" pin
-const MIN_NC_THRESH: u16 = 1000;
-
 /// Checks a pin bank.
 /// 1. checks to see if any pins are connected. If are connected, return None
 /// 2. if any show some kind of connectivity, returns Some([Option<&str>; 12]), where
@@ -136,21 +162,21 @@ fn check_pins(bank: PinBank) -> [(Option<&'static str>, u16); 12] {
 }
 
 /// Convenience function that just scans a bank and indicatse if an insertion was detected.
-fn check_insert(bank: PinBank) -> bool {
+fn check_insert(bank: PinBank, nc_thresh: u16) -> bool {
     let result = check_pins(bank);
     for (_name, val) in result {
-        if val < MIN_NC_THRESH {
+        if val < nc_thresh {
             return true;
         }
     }
     false
 }
 
-fn settling_check(bank: PinBank) -> [bool; 12] {
+fn settling_check(bank: PinBank, nc_thresh: u16) -> [bool; 12] {
     let result = check_pins(bank);
     let mut ret = [false; 12];
     for (index, &(_name, val)) in result.iter().enumerate() {
-        if val < MIN_NC_THRESH {
+        if val < nc_thresh {
             ret[index] = true;
         } else {
             ret[index] = false;
@@ -158,6 +184,36 @@ fn settling_check(bank: PinBank) -> [bool; 12] {
     }
     ret
 }
+/// Checks every pair of pins across *both* banks for cross-talk: drive one
+/// pin's mux while sensing a *different* pin's ADC channel, which should only
+/// read "connected" if the two are bridged (a solder splash, not an open),
+/// since the sensed pin was never itself muxed in. Covers the full 16-channel
+/// `ALL_PINS` set rather than each bank separately, since a short can just as
+/// easily bridge a lower pin to an upper one (e.g. the connector's shell/GND)
+/// as two pins within the same bank. Returns a 16x16 adjacency matrix over
+/// `ALL_PINS`'s indices.
+fn check_shorts(nc_thresh: u16) -> [[bool; 16]; 16] {
+    let mut adc = adc::Adc::new();
+    let mut shorted = [[false; 16]; 16];
+    for (drive_idx, &(drive_field, _)) in ALL_PINS.iter().enumerate() {
+        for (sense_idx, &(sense_field, _)) in ALL_PINS.iter().enumerate() {
+            if drive_idx == sense_idx {
+                continue;
+            }
+            let sense_ch = match adc::channel_for(sense_field) {
+                Some(ch) => ch,
+                None => continue,
+            };
+            if let Some(delta) = adc.read_cross(drive_field, sense_ch as u32) {
+                if delta < nc_thresh {
+                    shorted[drive_idx][sense_idx] = true;
+                }
+            }
+        }
+    }
+    shorted
+}
+
 fn results_equal(a: [bool; 12], b: [bool; 12]) -> bool {
     for (&x, &y) in a.iter().zip(b.iter()) {
         if x != y {
@@ -174,12 +230,78 @@ enum TestState {
     ReportResult,
 }
 
+/// Handles one decoded frame from the automated-test-fixture protocol (see
+/// `uart::FrameRx`): ping liveness, starting a test run programmatically, and
+/// reading back the last completed test's results as raw `(pin_index, delta)`
+/// tuples instead of the screen-scraped text the keystroke interface prints.
+fn dispatch_frame(
+    frame: &[u8],
+    force_run: &mut bool,
+    lower: &[(Option<&'static str>, u16); 12],
+    upper: &[(Option<&'static str>, u16); 12],
+    passing: bool,
+    done: bool,
+    stats: &storage::Stats,
+) {
+    if frame.len() < 2 {
+        return;
+    }
+    match (frame[0], frame[1]) {
+        (uart::SERVICE_PING, uart::SUBSERVICE_PING) => {
+            uart::send_frame(&[uart::SERVICE_PING, uart::SUBSERVICE_PING]);
+        }
+        (uart::SERVICE_TEST, uart::SUBSERVICE_TEST_START) => {
+            *force_run = true;
+            uart::send_frame(&[uart::SERVICE_TEST, uart::SUBSERVICE_TEST_START]);
+        }
+        (uart::SERVICE_TEST, uart::SUBSERVICE_TEST_READ_RESULTS) => {
+            let mut reply = [0u8; 3 + 16 * 3];
+            reply[0] = uart::SERVICE_TEST;
+            reply[1] = uart::SUBSERVICE_TEST_READ_RESULTS;
+            reply[2] = ((done as u8) << 1) | (passing as u8);
+            let mut pos = 3;
+            for (idx, &(_name, val)) in lower.iter().enumerate() {
+                reply[pos] = idx as u8;
+                reply[pos + 1..pos + 3].copy_from_slice(&val.to_le_bytes());
+                pos += 3;
+            }
+            for (idx, &(_name, val)) in upper.iter().take(4).enumerate() {
+                reply[pos] = (12 + idx) as u8;
+                reply[pos + 1..pos + 3].copy_from_slice(&val.to_le_bytes());
+                pos += 3;
+            }
+            uart::send_frame(&reply);
+        }
+        (uart::SERVICE_STATS, uart::SUBSERVICE_STATS_READ) => {
+            let mut reply = [0u8; 2 + 4 + 4 + 4 + storage::NUM_PINS];
+            reply[0] = uart::SERVICE_STATS;
+            reply[1] = uart::SUBSERVICE_STATS_READ;
+            reply[2..6].copy_from_slice(&stats.serial.to_le_bytes());
+            reply[6..10].copy_from_slice(&stats.pass_count.to_le_bytes());
+            reply[10..14].copy_from_slice(&stats.fail_count.to_le_bytes());
+            reply[14..14 + storage::NUM_PINS].copy_from_slice(&stats.fail_histogram);
+            uart::send_frame(&reply);
+        }
+        _ => (), // unknown service/subservice; ignore rather than wedging the link
+    }
+}
+
 #[entry]
 fn main() -> ! {
     logln!(LL::Info, "\r\n====UP5K==00");
     let mut crg_csr = CSR::new(HW_CRG_BASE as *mut u32);
     let mut ticktimer_csr = CSR::new(HW_TICKTIMER_BASE as *mut u32);
     let mut uart_state: uart::RxState = uart::RxState::BypassOnAwaitA;
+    let mut frame_rx = uart::FrameRx::new();
+    // Lets a SERVICE_TEST/SUBSERVICE_TEST_START frame enter the test loop the
+    // same way the physical switch does, and lets a SERVICE_TEST/
+    // SUBSERVICE_TEST_READ_RESULTS frame read back the last completed run
+    // even after the test loop below has exited.
+    let mut force_run = false;
+    let mut last_lower_result: [(Option<&'static str>, u16); 12] = [(None, u16::MAX); 12];
+    let mut last_upper_result: [(Option<&'static str>, u16); 12] = [(None, u16::MAX); 12];
+    let mut last_passing = false;
+    let mut last_test_done = false;
 
     // Initialize the no-MMU version of 'Xous' (an extremely old branch of it), which will give us
     // basic access to tasks and interrupts.
@@ -189,23 +311,45 @@ fn main() -> ! {
     time_init();
     logln!(LL::Debug, "time");
 
+    // SAFETY: CONFIG_BASE points at the config region xtask stamped into the
+    // memory-mapped boot flash; it's read-only and always CONFIG_LEN bytes.
+    let config = unsafe { config::load(CONFIG_BASE) };
+    logln!(LL::Debug, "config loaded, nc_thresh={}", config.nc_thresh);
+
     let _ = xous_nommu::syscalls::sys_interrupt_claim(
         utra::ticktimer::TICKTIMER_IRQ,
         ticktimer_int_handler,
     );
-    set_msleep_target_ticks(50);
+    set_msleep_target_ticks(WATCHDOG_PET_MS);
     ticktimer_csr.wfo(utra::ticktimer::EV_PENDING_ALARM, 1); // clear the pending signal just in case
     ticktimer_csr.wfo(utra::ticktimer::EV_ENABLE_ALARM, 1); // enable the interrupt
+    // `ticktimer_int_handler` above is now the sole owner of this IRQ and
+    // clears EV_PENDING_ALARM on every fire, which is all `sleep_ms`/
+    // `sleep_until` need to wfi instead of busy-waiting -- see
+    // `mark_irq_ready`'s doc comment for why `hal_time` doesn't claim the
+    // interrupt itself.
+    hal_time::mark_irq_ready();
 
     logln!(LL::Warn, "**WATCHDOG ON**");
     crg_csr.wfo(utra::crg::WATCHDOG_ENABLE, 1); // 1 = enable the watchdog reset
 
     // Drain the UART RX buffer
-    uart::drain_rx_buf();
+    uart::drain_rx_buf(&mut uart_state);
 
     let mut sbled = sbled::SbLed::new();
     sbled.idle();
     let mut screen = screen::Screen {};
+    if config.seed == [0u8; config::SEED_LEN] {
+        logln!(LL::Warn, "no per-device seed provisioned; signing with the shared default key");
+    }
+    let mut signer = sign::Signer::new(&config.seed);
+    let mut store = storage::Store::init();
+    let mut updater = updater::Updater::new();
+    log!(LL::Info, "report signing pubkey: ");
+    for b in signer.public_key() {
+        loghex!(LL::Info, "", b);
+    }
+    logln!(LL::Info, "");
 
     write!(screen, "#LCK").unwrap();
     write!(screen, "USB C Test Power On").unwrap();
@@ -219,28 +363,77 @@ fn main() -> ! {
         // Uart starts in bypass mode, so this won't start returning bytes
         // until after it sees the "AT\n" wake sequence (or "AT\r")
         let mut show_help = false;
-        if let Some(b) = uart::rx_byte(&mut uart_state) {
-            match b {
-                0x1B => {
-                    // In case of ANSI escape sequences (arrow keys, etc.) turn UART bypass mode
-                    // on to avoid the hassle of having to parse the escape sequences or deal
-                    // with whatever unintended commands they might accidentally trigger
-                    uart_state = uart::RxState::BypassOnAwaitA;
-                    logln!(LL::Debug, "UartRx off");
+        match uart::get_byte() {
+            Ok(Some(byte)) => {
+                // The COBS-framed protocol and the updater's raw sync-byte
+                // protocol share this wire but can't both parse the same
+                // byte: a firmware image routinely contains 0x00 (the COBS
+                // delimiter) and sync-byte values, and COBS traffic routinely
+                // contains the updater's sync bytes. `Updater::is_active()`
+                // tracks which session -- if any -- currently owns the
+                // stream; only it is fed while a transfer is underway, and
+                // only COBS is fed the rest of the time. `updater::feed` is
+                // still called unconditionally while idle so it can recognize
+                // its own out-of-band arming sequence.
+                let updater_was_active = updater.is_active();
+                let update_event = updater.feed(byte);
+                if !updater_was_active {
+                    if updater.is_active() {
+                        // Just armed: drop whatever COBS frame fragment was
+                        // mid-flight so it can't splice with real traffic
+                        // once the session ends.
+                        frame_rx.reset();
+                    } else if let Some(len) = frame_rx.feed(byte) {
+                        dispatch_frame(
+                            &frame_rx.frame()[..len],
+                            &mut force_run,
+                            &last_lower_result,
+                            &last_upper_result,
+                            last_passing,
+                            last_test_done,
+                            &store.stats(),
+                        );
+                    }
                 }
-                b'h' | b'H' | b'?' => show_help = true,
-                b'5' => {
-                    let now = TimeMs::now();
-                    loghex!(LL::Debug, "NowMs ", now.ms_high_word());
-                    loghexln!(LL::Debug, " ", now.ms_low_word());
+                match update_event {
+                    Some(updater::UpdateEvent::Committed) => {
+                        logln!(LL::Info, "firmware update committed to region {}; power-cycle to boot it", updater.region_id());
+                    }
+                    Some(updater::UpdateEvent::Rejected) => {
+                        logln!(LL::Warn, "firmware update rejected (CRC mismatch)");
+                    }
+                    Some(updater::UpdateEvent::Progress) | None => (),
+                }
+                if let Some(b) = uart::gate(&mut uart_state, byte) {
+                    match b {
+                        0x1B => {
+                            // In case of ANSI escape sequences (arrow keys, etc.) turn UART bypass mode
+                            // on to avoid the hassle of having to parse the escape sequences or deal
+                            // with whatever unintended commands they might accidentally trigger
+                            uart_state = uart::RxState::BypassOnAwaitA;
+                            logln!(LL::Debug, "UartRx off");
+                        }
+                        b'h' | b'H' | b'?' => show_help = true,
+                        b'5' => {
+                            let now = TimeMs::now();
+                            loghex!(LL::Debug, "NowMs ", now.ms_high_word());
+                            loghexln!(LL::Debug, " ", now.ms_low_word());
+                        }
+                        b'6' => stack_check(),
+                        _ => (),
+                    }
+                } else if uart_state == uart::RxState::Waking {
+                    logln!(LL::Debug, "UartRx on");
+                    uart_state = uart::RxState::BypassOff;
+                    show_help = true;
                 }
-                b'6' => stack_check(),
-                _ => (),
             }
-        } else if uart_state == uart::RxState::Waking {
-            logln!(LL::Debug, "UartRx on");
-            uart_state = uart::RxState::BypassOff;
-            show_help = true;
+            Ok(None) => (),
+            Err(_) => {
+                // get_byte() already logged the specific fault; resync the
+                // keystroke gate so stale/corrupted bytes aren't misread.
+                uart_state = uart::RxState::BypassOnAwaitA;
+            }
         }
         if show_help {
             log!(
@@ -255,12 +448,15 @@ fn main() -> ! {
         }
         ///////////////////////////// --------------------------------------
         ///////////////////////////// TEST LOOP ----------------------------
-        if dut_csr.rf(utra::dut::RUN_RUN) == 0 { // active low switch hit
-            delay_ms(10); // wait for the switch to debounce
-            while dut_csr.rf(utra::dut::RUN_RUN) == 0 { // wait for the switch to rise
-                delay_ms(10);
+        if dut_csr.rf(utra::dut::RUN_RUN) == 0 || force_run { // active low switch hit, or a SERVICE_TEST start frame
+            force_run = false;
+            if dut_csr.rf(utra::dut::RUN_RUN) == 0 {
+                sleep_ms(10); // wait for the switch to debounce
+                while dut_csr.rf(utra::dut::RUN_RUN) == 0 { // wait for the switch to rise
+                    sleep_ms(10);
+                }
+                sleep_ms(10); // another debounce period
             }
-            delay_ms(10); // another debounce period
             sbled.run();
 
             // test at least twice because we need to debounce the insertion
@@ -273,18 +469,35 @@ fn main() -> ! {
             let mut upper_finished = false;
             let mut bank = PinBank::Lower;
             let mut counter = 0;
+            let mut reported = false;
+            let mut total_fail = 0;
+            // Up to 8 shorted-pair names to print; a real connector with more
+            // bridges than that has bigger problems than a precise count.
+            let mut shorts: [Option<(&'static str, &'static str)>; 8] = [None; 8];
             logln!(LL::Info, "test start");
+            // Bounds how long a single insertion can sit in `WaitInsert`/`Measure`
+            // without producing a result, so a DUT that never settles (or never
+            // gets inserted) can't wedge the tester until the switch is cycled.
+            let timeout_at = TimeMs::now().add_ms(config.timeout_ms);
             loop {
                 if dut_csr.rf(utra::dut::RUN_RUN) == 0 { // active low switch hit exits the test
-                    delay_ms(10); // wait for the switch to debounce
+                    sleep_ms(10); // wait for the switch to debounce
                     while dut_csr.rf(utra::dut::RUN_RUN) == 0 { // wait for the switch to rise
-                        delay_ms(10);
+                        sleep_ms(10);
                     }
-                    delay_ms(10);
+                    sleep_ms(10);
                     sbled.idle();
                     logln!(LL::Info, "test exit");
                     break; // exit the loop
                 }
+                if test_state != TestState::ReportResult && TimeMs::now() >= timeout_at {
+                    logln!(LL::Warn, "test timeout");
+                    write!(screen, "#LCK").unwrap();
+                    write!(screen, "TEST TIMED OUT\n\rCycle switch to retry").unwrap();
+                    write!(screen, "#SYN").unwrap();
+                    sbled.idle();
+                    break; // exit the loop; switch must be cycled to try again
+                }
                 match test_state {
                     TestState::WaitInsert => {
                         counter = 0;
@@ -308,13 +521,13 @@ fn main() -> ! {
                         }
                         write!(screen, " \n\r").unwrap();
                         write!(screen, "#SYN").unwrap();
-                        if !lower_finished && check_insert(PinBank::Lower) {
+                        if !lower_finished && check_insert(PinBank::Lower, config.nc_thresh) {
                             logln!(LL::Info, "measure lower");
                             test_state = TestState::Measure;
                             bank = PinBank::Lower;
                             continue;
                         }
-                        if !upper_finished && check_insert(PinBank::Upper) {
+                        if !upper_finished && check_insert(PinBank::Upper, config.nc_thresh) {
                             logln!(LL::Info, "measure upper");
                             test_state = TestState::Measure;
                             bank = PinBank::Upper;
@@ -338,7 +551,7 @@ fn main() -> ! {
                         write!(screen, " \n\r").unwrap();
                         write!(screen, "#SYN").unwrap();
                         counter += 1;
-                        let new_result = settling_check(PinBank::Lower);
+                        let new_result = settling_check(PinBank::Lower, config.nc_thresh);
                         if results_equal(new_result, last_result) {
                             stabilize += 1;
                         } else {
@@ -358,21 +571,93 @@ fn main() -> ! {
                         }
                     }
                     TestState::ReportResult => {
-                        let mut passing = true;
-                        let mut total_fail = 0;
-                        for (_name, val) in lower_result {
-                            if val > MIN_NC_THRESH {
-                                passing = false;
-                                total_fail += 1;
+                        // `ReportResult` re-renders every loop tick until the
+                        // DUT is removed, so everything that's expensive (the
+                        // short-circuit sweep) or must happen exactly once
+                        // (signing, stats, the monotonic counter) is gated on
+                        // `reported` and its results cached in `total_fail`/
+                        // `shorts`/`last_passing` for the redraws that follow.
+                        if !reported {
+                            reported = true;
+                            let mut passing = true;
+                            for (_name, val) in lower_result {
+                                if val > config.nc_thresh {
+                                    passing = false;
+                                    total_fail += 1;
+                                }
                             }
-                        }
-                        for (_name, val) in upper_result {
-                            if val > MIN_NC_THRESH {
-                                passing = false;
-                                total_fail += 1;
+                            for (_name, val) in upper_result {
+                                if val > config.nc_thresh {
+                                    passing = false;
+                                    total_fail += 1;
+                                }
                             }
+
+                            // Opens are only half the story: a bridged pair
+                            // can read "connected" on both sides individually
+                            // while still being the actual defect. Swept
+                            // across all 16 pins so a short bridging a lower
+                            // pin to an upper one is caught too, not just
+                            // shorts within the same bank.
+                            let shorts_matrix = check_shorts(config.nc_thresh);
+                            let mut short_count = 0;
+                            for i in 0..16 {
+                                for j in (i + 1)..16 {
+                                    if shorts_matrix[i][j] || shorts_matrix[j][i] {
+                                        passing = false;
+                                        if short_count < shorts.len() {
+                                            shorts[short_count] = Some((ALL_PINS[i].1, ALL_PINS[j].1));
+                                            short_count += 1;
+                                        }
+                                    }
+                                }
+                            }
+
+                            last_lower_result = lower_result;
+                            last_upper_result = upper_result;
+                            last_passing = passing;
+                            last_test_done = true;
+
+                            // Persist running yield stats before anything
+                            // else: a signed report can be re-derived from
+                            // the measurements, but the cumulative counters
+                            // can't be recovered if we reset before writing.
+                            let mut fail_pins = [0usize; storage::NUM_PINS];
+                            let mut fail_count = 0;
+                            for (idx, &(_name, val)) in lower_result.iter().enumerate() {
+                                if val > config.nc_thresh && fail_count < fail_pins.len() {
+                                    fail_pins[fail_count] = idx;
+                                    fail_count += 1;
+                                }
+                            }
+                            for (idx, &(_name, val)) in upper_result.iter().take(4).enumerate() {
+                                if val > config.nc_thresh && fail_count < fail_pins.len() {
+                                    fail_pins[fail_count] = 12 + idx;
+                                    fail_count += 1;
+                                }
+                            }
+                            store.record_test(passing, &fail_pins[..fail_count]);
+
+                            // Tamper-evident attestation: sign this report
+                            // before announcing pass/fail so a transcribed or
+                            // forwarded result can be checked against the
+                            // device's public key later.
+                            let (report, signature) =
+                                signer.sign_report(&lower_result, &upper_result, passing);
+                            write!(screen, "SIG:").unwrap();
+                            for &b in report.iter().chain(signature.iter()) {
+                                write!(screen, "{:02x}", b).unwrap();
+                            }
+                            write!(screen, "\n\r").unwrap();
+                            let mut signed_frame = [0u8; 2 + sign::REPORT_LEN + sign::SIGNATURE_LEN];
+                            signed_frame[0] = uart::SERVICE_TEST;
+                            signed_frame[1] = uart::SUBSERVICE_TEST_SIGNED_REPORT;
+                            signed_frame[2..2 + sign::REPORT_LEN].copy_from_slice(&report);
+                            signed_frame[2 + sign::REPORT_LEN..].copy_from_slice(&signature);
+                            uart::send_frame(&signed_frame);
                         }
-                        if passing {
+
+                        if last_passing {
                             sbled.pass();
                             write!(screen, "   PASS PASS PASS\n\r").unwrap();
                             write!(screen, " \n\r").unwrap();
@@ -386,7 +671,7 @@ fn main() -> ! {
                             let mut lines = 0;
                             for (maybe_name, val) in lower_result {
                                 if let Some(name) = maybe_name {
-                                    if val >= MIN_NC_THRESH {
+                                    if val >= config.nc_thresh {
                                         if lines < 5 {
                                             write!(screen, " {}", name).unwrap();
                                             lines += 1;
@@ -396,7 +681,7 @@ fn main() -> ! {
                             }
                             for (maybe_name, val) in upper_result {
                                 if let Some(name) = maybe_name {
-                                    if val >= MIN_NC_THRESH {
+                                    if val >= config.nc_thresh {
                                         if lines < 5 {
                                             write!(screen, " {}", name).unwrap();
                                             lines += 1;
@@ -404,6 +689,11 @@ fn main() -> ! {
                                     }
                                 }
                             }
+                            for maybe_short in shorts {
+                                if let Some((a, b)) = maybe_short {
+                                    write!(screen, "SHORT: {}<->{}\n\r", a, b).unwrap();
+                                }
+                            }
                         }
                     }
                 }
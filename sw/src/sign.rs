@@ -0,0 +1,82 @@
+//! Ed25519 signing of completed test reports.
+//!
+//! A pass/fail verdict and its per-pin measurements are easy to forge or
+//! mis-transcribe once they've left the tester as plain text, so each
+//! completed test is also serialized into a compact, fixed-layout report and
+//! signed with a device key. A host can later verify a batch of connectors
+//! against the shipped public key to confirm they were actually tested by
+//! authorized hardware, not just typed into a spreadsheet.
+//!
+//! Uses `salty`, a `no_std`/no-allocator Ed25519 implementation that runs on
+//! RISC-V without an FPU -- the same constraint that rules out most other
+//! crypto crates for this target.
+
+use salty::Keypair;
+
+/// Bumped if the report layout below ever changes, so an old verifier can at
+/// least refuse to misinterpret a new-format report instead of silently
+/// misreading it.
+pub const REPORT_VERSION: u8 = 1;
+
+/// version (1) + 12 lower channel deltas (2 each) + 4 upper channel deltas
+/// (2 each) + pass flag (1) + test counter (4).
+pub const REPORT_LEN: usize = 1 + 12 * 2 + 4 * 2 + 1 + 4;
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Signs completed test reports with a device-held Ed25519 key and keeps the
+/// monotonic counter that's folded into each report.
+pub struct Signer {
+    keypair: Keypair,
+    counter: u32,
+}
+
+impl Signer {
+    /// `seed` is the per-device key provisioned into the config region at
+    /// manufacturing time (see `config::Config::seed`). An all-zero seed
+    /// means the unit was never provisioned -- every such unit shares the
+    /// same key, so `main` logs a warning at boot when that's the case.
+    pub fn new(seed: &[u8; 32]) -> Self {
+        Signer { keypair: Keypair::from(seed), counter: 0 }
+    }
+
+    /// The public key a host uses to verify reports this unit signs.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.keypair.public.to_bytes()
+    }
+
+    /// Serializes and signs one completed test's report. Returns the
+    /// serialized report alongside its detached signature; the caller is
+    /// responsible for emitting both together (screen, UART, ...) since a
+    /// signature is meaningless without the bytes it covers.
+    ///
+    /// Bumps the internal counter on every call, so the same measurements
+    /// signed twice still produce two distinguishable reports.
+    pub fn sign_report(
+        &mut self,
+        lower: &[(Option<&'static str>, u16); 12],
+        upper: &[(Option<&'static str>, u16); 12],
+        passing: bool,
+    ) -> ([u8; REPORT_LEN], [u8; SIGNATURE_LEN]) {
+        let mut report = [0u8; REPORT_LEN];
+        let mut pos = 0;
+        report[pos] = REPORT_VERSION;
+        pos += 1;
+        for &(_name, val) in lower.iter() {
+            report[pos..pos + 2].copy_from_slice(&val.to_le_bytes());
+            pos += 2;
+        }
+        for &(_name, val) in upper.iter().take(4) {
+            report[pos..pos + 2].copy_from_slice(&val.to_le_bytes());
+            pos += 2;
+        }
+        report[pos] = passing as u8;
+        pos += 1;
+        report[pos..pos + 4].copy_from_slice(&self.counter.to_le_bytes());
+        pos += 4;
+        debug_assert_eq!(pos, REPORT_LEN);
+
+        let signature = self.keypair.sign(&report);
+        self.counter = self.counter.wrapping_add(1);
+        (report, signature.to_bytes())
+    }
+}
@@ -0,0 +1,61 @@
+//! Driver for the board's SPI NOR boot flash ("spinor").
+//!
+//! Reads go straight through the flash's memory-mapped window -- the same
+//! one `config::load` and the loader's manifest walk already read from, no
+//! command sequencing needed. Erase and program go through the command/
+//! status CSR pair instead, one flash-controller operation at a time.
+
+use utralib::generated::{utra, CSR, HW_SPINOR_BASE};
+
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Base of the flash's memory-mapped read window (same region the boot path
+/// and `config::load` read from). `pub(crate)` so other modules that need to
+/// compute an absolute flash address (e.g. `main`'s `CONFIG_BASE`) derive it
+/// from this one literal instead of retyping it.
+pub(crate) const FLASH_MMAP_BASE: u32 = 0x2000_0000;
+const MMAP_BASE: *const u8 = FLASH_MMAP_BASE as *const u8;
+
+pub struct Spi {
+    csr: CSR<u32>,
+}
+
+impl Spi {
+    pub fn new() -> Self {
+        Spi { csr: CSR::new(HW_SPINOR_BASE as *mut u32) }
+    }
+
+    fn wait_wip_clear(&mut self) {
+        while self.csr.rf(utra::spinor::STATUS_WIP) != 0 {}
+    }
+
+    /// Reads `buf.len()` bytes starting at `offset` out of the flash's
+    /// memory-mapped window.
+    pub fn read(&self, offset: u32, buf: &mut [u8]) {
+        let src = unsafe {
+            core::slice::from_raw_parts(MMAP_BASE.add(offset as usize), buf.len())
+        };
+        buf.copy_from_slice(src);
+    }
+
+    /// Erases the 4KiB sector containing `offset`.
+    pub fn erase_sector(&mut self, offset: u32) {
+        self.wait_wip_clear();
+        self.csr.wfo(utra::spinor::CMD_ARG_CMD_ARG, offset & !(SECTOR_SIZE as u32 - 1));
+        self.csr.wfo(utra::spinor::COMMAND_ERASE_SECTOR, 1);
+        self.wait_wip_clear();
+    }
+
+    /// Programs `data` into already-erased flash starting at `offset`, one
+    /// byte per controller operation. Byte-at-a-time avoids having to reason
+    /// about page-boundary crossings for the small records `storage` writes.
+    pub fn program(&mut self, offset: u32, data: &[u8]) {
+        self.wait_wip_clear();
+        for (i, &byte) in data.iter().enumerate() {
+            self.csr.wfo(utra::spinor::WDATA_WDATA, byte as u32);
+            self.csr.wfo(utra::spinor::CMD_ARG_CMD_ARG, offset + i as u32);
+            self.csr.wfo(utra::spinor::COMMAND_PROGRAM_BYTE, 1);
+            self.wait_wip_clear();
+        }
+    }
+}
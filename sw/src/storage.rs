@@ -0,0 +1,179 @@
+//! Append-only pass/fail statistics log on the SPI flash.
+//!
+//! Built on `spi`'s raw sector-erase/program primitives. Each completed test
+//! appends one fixed-size, CRC-guarded record holding the *cumulative*
+//! counters (not a delta), so recovering current state on boot is just
+//! "find the last record whose CRC checks out and trust it" -- a mid-write
+//! brownout (the watchdog path in `ticktimer_int_handler` can fire at any
+//! time) only ever loses the one record in flight, never the whole log.
+//!
+//! The region is used as a simple ring of fixed-size slots: appends advance
+//! through it until it's full, at which point it wraps back to slot 0 after
+//! erasing the sector(s) it's about to overwrite.
+
+use crate::spi::{Spi, SECTOR_SIZE};
+
+/// Flash offset of the stats region. xtask's manifest (see `create_image`'s
+/// region table) doesn't carve this out, so it's a fixed offset past the end
+/// of the config region, unlike `main`'s `CONFIG_BASE`, which is derived
+/// from the region table's layout.
+pub const STORAGE_BASE: u32 = 0x9_1000;
+pub const STORAGE_LEN: u32 = 2 * SECTOR_SIZE as u32; // two sectors, ring-buffered
+
+/// 12 lower + 4 upper pins, matching `sign::Signer`'s report channel order.
+pub const NUM_PINS: usize = 16;
+
+const RECORD_MAGIC: u8 = 0x5A;
+const RECORD_LEN: usize = 1 /* magic */ + 4 /* serial */ + 4 /* pass_count */ + 4 /* fail_count */
+    + NUM_PINS /* fail histogram, saturating per-pin counts */
+    + 4 /* crc32 */;
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    /// Incrementing unit serial number: one per completed test, across resets.
+    pub serial: u32,
+    pub pass_count: u32,
+    pub fail_count: u32,
+    pub fail_histogram: [u8; NUM_PINS],
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats { serial: 0, pass_count: 0, fail_count: 0, fail_histogram: [0; NUM_PINS] }
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    // Matches xtask's / updater.rs's CRC_32_ISO_HDLC bitwise routine.
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xedb8_8320;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
+fn serialize(stats: &Stats) -> [u8; RECORD_LEN] {
+    let mut rec = [0u8; RECORD_LEN];
+    rec[0] = RECORD_MAGIC;
+    rec[1..5].copy_from_slice(&stats.serial.to_le_bytes());
+    rec[5..9].copy_from_slice(&stats.pass_count.to_le_bytes());
+    rec[9..13].copy_from_slice(&stats.fail_count.to_le_bytes());
+    rec[13..13 + NUM_PINS].copy_from_slice(&stats.fail_histogram);
+    let crc = crc32(&rec[..13 + NUM_PINS]);
+    rec[13 + NUM_PINS..].copy_from_slice(&crc.to_le_bytes());
+    rec
+}
+
+fn deserialize(rec: &[u8]) -> Option<Stats> {
+    if rec.len() < RECORD_LEN || rec[0] != RECORD_MAGIC {
+        return None;
+    }
+    let crc_pos = 13 + NUM_PINS;
+    let expected = u32::from_le_bytes(rec[crc_pos..crc_pos + 4].try_into().ok()?);
+    if crc32(&rec[..crc_pos]) != expected {
+        return None;
+    }
+    let mut fail_histogram = [0u8; NUM_PINS];
+    fail_histogram.copy_from_slice(&rec[13..crc_pos]);
+    Some(Stats {
+        serial: u32::from_le_bytes(rec[1..5].try_into().ok()?),
+        pass_count: u32::from_le_bytes(rec[5..9].try_into().ok()?),
+        fail_count: u32::from_le_bytes(rec[9..13].try_into().ok()?),
+        fail_histogram,
+    })
+}
+
+pub struct Store {
+    spi: Spi,
+    stats: Stats,
+    /// Offset, relative to `STORAGE_BASE`, of the next free slot.
+    next_offset: u32,
+}
+
+impl Store {
+    /// Scans the whole region for the last record whose CRC checks out and
+    /// starts appending right after it. A fully-erased region (every slot
+    /// fails its CRC check) starts fresh with `Stats::default()`.
+    ///
+    /// The slot right after the last valid record is usually just blank
+    /// flash (erase leaves it `0xFF`-filled), but a brownout mid-`program`
+    /// can leave it holding a partially-written, non-blank record that still
+    /// fails its CRC. Flash can only clear bits on `program`, so writing a
+    /// fresh record on top of that leftover would corrupt it -- but erasing
+    /// its whole 4KiB sector to fix that would take out every already-good
+    /// record sharing the sector with it too (sector size dwarfs `RECORD_LEN`).
+    /// Instead, treat a dirty record as a single skippable hole: leave it on
+    /// flash untouched, step `next_offset` past it, and keep scanning, so
+    /// appends simply resume one slot later and the dead 33 bytes just sit
+    /// there until the next ring wrap erases the whole region anyway.
+    pub fn init() -> Self {
+        let spi = Spi::new();
+        let mut stats = Stats::default();
+        let mut next_offset = 0;
+        let mut buf = [0u8; RECORD_LEN];
+        let mut offset = 0u32;
+        while offset + RECORD_LEN as u32 <= STORAGE_LEN {
+            spi.read(STORAGE_BASE + offset, &mut buf);
+            match deserialize(&buf) {
+                Some(s) => {
+                    stats = s;
+                    next_offset = offset + RECORD_LEN as u32;
+                }
+                None => {
+                    if buf.iter().all(|&b| b == 0xFF) {
+                        break; // genuinely blank: the real end of the log
+                    }
+                    // Dirty leftover from an interrupted append: skip over it
+                    // and keep scanning, rather than treating it as the end of
+                    // the log -- there may be more valid records past it.
+                    next_offset = offset + RECORD_LEN as u32;
+                }
+            }
+            offset += RECORD_LEN as u32;
+        }
+        Store { spi, stats, next_offset }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Bumps the running counters for one completed test and appends the new
+    /// cumulative snapshot to flash. `fail_pins` holds the indices (0..16,
+    /// matching `sign::Signer`'s report layout) of pins that failed this run.
+    pub fn record_test(&mut self, passing: bool, fail_pins: &[usize]) {
+        if passing {
+            self.stats.pass_count = self.stats.pass_count.saturating_add(1);
+        } else {
+            self.stats.fail_count = self.stats.fail_count.saturating_add(1);
+            for &idx in fail_pins {
+                if idx < NUM_PINS {
+                    self.stats.fail_histogram[idx] = self.stats.fail_histogram[idx].saturating_add(1);
+                }
+            }
+        }
+        self.stats.serial = self.stats.serial.saturating_add(1);
+
+        if self.next_offset + RECORD_LEN as u32 > STORAGE_LEN {
+            // Ring wrapped: erase the sector(s) we're about to reuse before
+            // writing into them again.
+            let mut erase_offset = 0;
+            while erase_offset < STORAGE_LEN {
+                self.spi.erase_sector(STORAGE_BASE + erase_offset);
+                erase_offset += SECTOR_SIZE as u32;
+            }
+            self.next_offset = 0;
+        }
+
+        let rec = serialize(&self.stats);
+        self.spi.program(STORAGE_BASE + self.next_offset, &rec);
+        self.next_offset += RECORD_LEN as u32;
+    }
+}
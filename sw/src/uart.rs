@@ -0,0 +1,253 @@
+//! Debug UART driver.
+//!
+//! Two interfaces share the same wire:
+//!  - The human keystroke interface: RX starts gated (`BypassOnAwaitA`) so a
+//!    noisy bench cable can't accidentally drive the tester; typing "AT"
+//!    followed by a line ending (`Waking`) wakes it up so single keystrokes
+//!    reach `main()` (`BypassOff`). This remains the fallback mode.
+//!  - The framed binary protocol: every byte is also fed to a COBS frame
+//!    decoder regardless of bypass state, so an automated test fixture host
+//!    can drive the tester without needing to screen-scrape ASCII or do the
+//!    "AT" handshake. Frames are `[service][subservice][payload...]`.
+
+use debug::{logln, LL};
+use utralib::generated::{utra, CSR, HW_UART_BASE};
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum RxState {
+    /// Waiting for the start of an "AT\r"/"AT\n" wake sequence.
+    BypassOnAwaitA,
+    /// Saw "AT" and a line ending; main() flips this to BypassOff and prints the help banner.
+    Waking,
+    /// Wake sequence complete; bytes pass straight through as keystrokes.
+    BypassOff,
+}
+
+/// An RX-side fault latched by the UART hardware, as opposed to a framing
+/// problem `FrameRx` catches on the decoded payload itself.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RxError {
+    /// A byte arrived while the RX FIFO was already full and was lost.
+    Overrun,
+    /// A break condition (line held low past a full frame) was seen.
+    Break,
+    /// The received byte failed the parity check.
+    Parity,
+}
+
+/// Reads one raw byte off the wire, with no interpretation beyond surfacing
+/// hardware-latched RX faults. The main loop feeds a clean byte to both
+/// `gate` (the keystroke interface) and, while no `updater` session owns the
+/// stream, a `FrameRx` (the binary protocol) so neither consumer steals bytes
+/// from the other.
+///
+/// Error status bits are write-1-to-clear and latched independently of the
+/// RX FIFO, so each is cleared as soon as it's reported here -- otherwise a
+/// single stuck bit would poison every subsequent read.
+pub fn get_byte() -> Result<Option<u8>, RxError> {
+    let mut uart_csr = CSR::new(HW_UART_BASE as *mut u32);
+    if uart_csr.rf(utra::uart::OVERRUN_OVERRUN) != 0 {
+        uart_csr.wfo(utra::uart::OVERRUN_OVERRUN, 1);
+        logln!(LL::Error, "uart: RX overrun");
+        return Err(RxError::Overrun);
+    }
+    if uart_csr.rf(utra::uart::BREAK_BREAK) != 0 {
+        uart_csr.wfo(utra::uart::BREAK_BREAK, 1);
+        logln!(LL::Error, "uart: RX break");
+        return Err(RxError::Break);
+    }
+    if uart_csr.rf(utra::uart::PARITY_PARITY) != 0 {
+        uart_csr.wfo(utra::uart::PARITY_PARITY, 1);
+        logln!(LL::Error, "uart: RX parity error");
+        return Err(RxError::Parity);
+    }
+    if uart_csr.rf(utra::uart::RXEMPTY_RXEMPTY) == 1 {
+        Ok(None)
+    } else {
+        Ok(Some(uart_csr.rf(utra::uart::RXTX_RXTX) as u8))
+    }
+}
+
+pub fn putc(c: u8) {
+    let mut uart_csr = CSR::new(HW_UART_BASE as *mut u32);
+    while uart_csr.rf(utra::uart::TXFULL_TXFULL) == 1 {}
+    uart_csr.wfo(utra::uart::RXTX_RXTX, c as u32);
+}
+
+/// Drains any bytes sitting in the RX FIFO without interpreting them -- used
+/// at boot so stale line noise isn't mistaken for a wake sequence. If an
+/// overrun or other fault shows up mid-drain, `state` is reset to
+/// `BypassOnAwaitA` so whatever garbage caused it can't be misread as a
+/// partial "AT" sequence or stray keystrokes once draining finishes.
+pub fn drain_rx_buf(state: &mut RxState) {
+    loop {
+        match get_byte() {
+            Ok(Some(_)) => continue,
+            Ok(None) => break,
+            Err(_) => *state = RxState::BypassOnAwaitA,
+        }
+    }
+}
+
+/// Tracks how much of "AT" has been seen while gated; lives outside `RxState`
+/// since the public enum only needs to expose the three states callers react to.
+static mut SEEN_A: bool = false;
+static mut SEEN_T: bool = false;
+
+/// Applies the "AT\n" bypass gate to one already-read byte. Returns
+/// `Some(byte)` only once the bypass is off; while still waiting for the wake
+/// sequence it consumes the byte silently and returns `None`.
+pub fn gate(state: &mut RxState, byte: u8) -> Option<u8> {
+    match *state {
+        RxState::BypassOnAwaitA => {
+            unsafe {
+                if SEEN_T {
+                    SEEN_T = false;
+                    if byte == b'\r' || byte == b'\n' {
+                        *state = RxState::Waking;
+                    }
+                } else if SEEN_A {
+                    SEEN_A = false;
+                    if byte == b'T' || byte == b't' {
+                        SEEN_T = true;
+                    }
+                } else if byte == b'A' || byte == b'a' {
+                    SEEN_A = true;
+                }
+            }
+            None
+        }
+        RxState::Waking | RxState::BypassOff => Some(byte),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Framed binary protocol
+// ---------------------------------------------------------------------------
+
+pub const SERVICE_PING: u8 = 0;
+pub const SERVICE_TEST: u8 = 1;
+/// End-of-shift yield reporting: dumps the cumulative stats `storage::Store`
+/// keeps on flash.
+pub const SERVICE_STATS: u8 = 2;
+pub const SUBSERVICE_STATS_READ: u8 = 0;
+pub const SUBSERVICE_PING: u8 = 0;
+pub const SUBSERVICE_TEST_START: u8 = 0;
+pub const SUBSERVICE_TEST_READ_RESULTS: u8 = 1;
+/// Unsolicited: pushed out right after a test completes, carrying the signed
+/// report from `sign::Signer` (see `main`'s `TestState::ReportResult`). Never
+/// sent in response to a host frame, so it has no matching dispatch arm.
+pub const SUBSERVICE_TEST_SIGNED_REPORT: u8 = 2;
+
+const MAX_FRAME: usize = 300; // header (2) + 16 x (pin_index, u16) + pass flag, COBS-expanded
+
+/// Accumulates COBS-encoded bytes off the wire and decodes a complete frame
+/// in place once it sees the trailing `0x00` delimiter.
+pub struct FrameRx {
+    buf: [u8; MAX_FRAME],
+    pos: usize,
+}
+
+impl FrameRx {
+    pub fn new() -> Self {
+        FrameRx { buf: [0; MAX_FRAME], pos: 0 }
+    }
+
+    /// Feed one raw UART byte in. Returns `Some(len)` -- the decoded frame is
+    /// then `self.frame()[..len]` -- once a full frame has landed.
+    pub fn feed(&mut self, byte: u8) -> Option<usize> {
+        if byte == 0x00 {
+            let len = cobs_decode(&mut self.buf[..self.pos]);
+            self.pos = 0;
+            return Some(len);
+        }
+        if self.pos < self.buf.len() {
+            self.buf[self.pos] = byte;
+            self.pos += 1;
+        } else {
+            // overran our buffer; drop the frame and resync on the next 0x00
+            self.pos = 0;
+        }
+        None
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Discards whatever partial frame has been accumulated so far. Used
+    /// when something else (e.g. `updater::Updater` arming a raw transfer
+    /// session) takes over the byte stream out from under an in-progress
+    /// frame, so stray leftover bytes don't corrupt the next real one.
+    pub fn reset(&mut self) {
+        self.pos = 0;
+    }
+}
+
+/// COBS-encodes `input` into `output` (which must be at least `input.len() +
+/// input.len() / 254 + 2` bytes), appending the trailing `0x00` delimiter.
+/// Returns the number of bytes written, delimiter included.
+pub fn cobs_encode(input: &[u8], output: &mut [u8]) -> usize {
+    let mut out_pos = 1; // leave room for the first code byte
+    let mut code_pos = 0;
+    let mut code = 1u8;
+
+    for &byte in input {
+        if byte == 0 {
+            output[code_pos] = code;
+            code_pos = out_pos;
+            out_pos += 1;
+            code = 1;
+        } else {
+            output[out_pos] = byte;
+            out_pos += 1;
+            code += 1;
+            if code == 0xFF {
+                output[code_pos] = code;
+                code_pos = out_pos;
+                out_pos += 1;
+                code = 1;
+            }
+        }
+    }
+    output[code_pos] = code;
+    output[out_pos] = 0x00;
+    out_pos + 1
+}
+
+/// Decodes a COBS frame in place (delimiter already stripped by the caller).
+/// Returns the decoded payload length.
+fn cobs_decode(buf: &mut [u8]) -> usize {
+    let mut read = 0;
+    let mut write = 0;
+    let len = buf.len();
+    while read < len {
+        let code = buf[read] as usize;
+        if code == 0 || read + code > len + 1 {
+            break; // malformed frame
+        }
+        read += 1;
+        for _ in 1..code {
+            if read >= len {
+                break;
+            }
+            buf[write] = buf[read];
+            write += 1;
+            read += 1;
+        }
+        if code != 0xFF && read < len {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    write
+}
+
+/// COBS-encodes and sends `payload` out over the UART.
+pub fn send_frame(payload: &[u8]) {
+    let mut out = [0u8; MAX_FRAME];
+    let n = cobs_encode(payload, &mut out);
+    for &b in &out[..n] {
+        putc(b);
+    }
+}
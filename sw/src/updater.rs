@@ -0,0 +1,349 @@
+//! Native firmware-update receiver, replacing the old host-side `usb_update.py`
+//! shell-out. Host (xtask's `send_firmware_update`) sends one header frame
+//! describing the whole image, then fixed-size data frames each carrying a
+//! sequence number and CRC16; we ACK each accepted frame and NAK (with the
+//! sequence number we're expecting) on a CRC mismatch or reordering so the
+//! host retries just that frame. Only once the whole image's CRC32 checks out
+//! do we report success -- a bad transfer never touches the existing image.
+//!
+//! Bytes are fed in by the caller (see `feed`) rather than read straight off
+//! the UART here, the same way `uart::FrameRx` works -- `main`'s loop already
+//! owns the one `uart::get_byte()` call per iteration, and a second consumer
+//! reading the RX FIFO directly would steal bytes out from under it.
+//!
+//! This is also why every byte in must clear `ENTER_MAGIC` first: the image
+//! data this protocol transfers routinely contains `0x00` (the COBS frame
+//! decoder's delimiter) and bytes that happen to equal `HEADER_SYNC`/
+//! `DATA_SYNC`, and the COBS stream in turn routinely contains bytes that
+//! happen to equal those sync bytes. Without a handshake the two parsers
+//! would periodically steal each other's bytes -- a firmware image byte
+//! sequence that looks like a `SERVICE_TEST`/`SUBSERVICE_TEST_START` COBS
+//! frame would kick off a live test mid-flash-write, or COBS noise would
+//! desync an in-progress transfer. `main`'s loop only calls `feed` while
+//! `is_active()` is true, and only calls the COBS decoder while it's false,
+//! so exactly one of the two ever sees a given byte.
+
+use crate::spi::{Spi, SECTOR_SIZE};
+use crate::uart::putc;
+
+/// Out-of-band handshake that arms the updater; chosen bytes avoid `0x00` (a
+/// COBS delimiter) so spotting them mid-sequence can't terminate a COBS frame
+/// early. `main`'s loop feeds every raw byte to `Updater::feed` regardless of
+/// COBS state so this can be recognized no matter what the COBS decoder is
+/// doing with the same bytes; an arbitrary COBS stream reproducing these 4
+/// bytes back-to-back by chance is the same order of risk already accepted
+/// elsewhere in this protocol (e.g. a CRC32 collision hiding a bad transfer).
+const ENTER_MAGIC: [u8; 4] = [0xA5, 0x5A, 0xC3, 0x3C];
+const HEADER_SYNC: u8 = 0xAA;
+const DATA_SYNC: u8 = 0xBB;
+/// Asks which kernel slot is currently active, so the host can target the
+/// *inactive* one with the update that follows. A single byte in, a single
+/// byte (the active_slot value, 0 or 1) out -- no framing needed.
+const QUERY_SYNC: u8 = 0xCC;
+const DATA_FRAME_SIZE: usize = 256;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+
+/// Byte offset of `slot_state.active_slot` within the manifest header, see
+/// xtask's `SlotState`/loader.S's manifest layout comment.
+const SLOT_STATE_OFFSET: u32 = 0x44;
+
+// Mirrors xtask's `create_image` region sizing and loader.S's region table
+// (region 0 = gateware, 1 = loader, 2 = kernel slot A, 3 = kernel slot B, 4 =
+// config). Keep in sync with main.rs's CONFIG_OFFSET derivation if xtask's
+// region layout ever changes.
+const HEADER_SIZE: u32 = 4096;
+const GATEWARE_REGION: u32 = 104 * 1024;
+const LOADER_REGION: u32 = 4096;
+const KERNEL_REGION: u32 = 76 * 1024;
+const KERNEL_A_OFFSET: u32 = HEADER_SIZE + GATEWARE_REGION + LOADER_REGION;
+const KERNEL_B_OFFSET: u32 = KERNEL_A_OFFSET + KERNEL_REGION;
+
+const REGION_KERNEL_A: u8 = 2;
+const REGION_KERNEL_B: u8 = 3;
+
+/// Maps a wire `region_id` to the flash offset an update to it should land at.
+/// Only the kernel slots are updatable over this path; anything else (e.g.
+/// the gateware or loader region) isn't accepted since corrupting either of
+/// those has no A/B fallback to recover from.
+fn region_offset(region_id: u8) -> Option<u32> {
+    match region_id {
+        REGION_KERNEL_A => Some(KERNEL_A_OFFSET),
+        REGION_KERNEL_B => Some(KERNEL_B_OFFSET),
+        _ => None,
+    }
+}
+
+fn send_ack() {
+    putc(ACK);
+}
+fn send_nak(expected_seq: u16) {
+    putc(NAK);
+    let b = expected_seq.to_le_bytes();
+    putc(b[0]);
+    putc(b[1]);
+}
+
+/// CRC16/CCITT-FALSE, matches xtask's `crc16()`.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// CRC32/ISO-HDLC, matches xtask's `crc32()` (the `crc` crate, `CRC_32_ISO_HDLC`).
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+enum RxState {
+    /// Disarmed: not a valid transfer session, and the only thing `feed`
+    /// looks for is `ENTER_MAGIC` arriving byte-for-byte. `matched` counts
+    /// how much of the magic has been seen consecutively so far.
+    AwaitMagic { matched: usize },
+    /// Armed, waiting for the next command byte (`QUERY_SYNC`/`HEADER_SYNC`/
+    /// `DATA_SYNC`).
+    Idle,
+    Header { buf: [u8; 9], pos: usize },
+    DataHeader { buf: [u8; 4], pos: usize },
+    DataBody { seq: u16, len: usize, buf: [u8; DATA_FRAME_SIZE], pos: usize },
+    DataCrc { seq: u16, len: usize, buf: [u8; DATA_FRAME_SIZE], crc_buf: [u8; 2], pos: usize },
+}
+
+/// Outcome of feeding one byte in: nothing yet, a transfer still in progress,
+/// or a terminal result once the whole image has been checked.
+pub enum UpdateEvent {
+    Progress,
+    Committed,
+    Rejected,
+}
+
+pub struct Updater {
+    spi: Spi,
+    state: RxState,
+    region_id: u8,
+    /// Flash offset the current transfer writes to, once `region_id` has
+    /// resolved to a known region. `None` for an unrecognized region_id, in
+    /// which case data frames are still CRC-checked (so the host's framing
+    /// stays in sync) but never programmed to flash.
+    write_base: Option<u32>,
+    total_len: u32,
+    image_crc32: u32,
+    received_len: u32,
+    running_crc32: u32,
+    next_seq: u16,
+    /// Scratch room for `commit_slot`'s read-modify-erase-write of the
+    /// manifest header. A struct field rather than a local in `commit_slot`
+    /// so the 4KiB it needs doesn't spike the call stack.
+    header_scratch: [u8; HEADER_SIZE as usize],
+}
+
+impl Updater {
+    pub fn new() -> Self {
+        Updater {
+            spi: Spi::new(),
+            state: RxState::AwaitMagic { matched: 0 },
+            region_id: 0,
+            write_base: None,
+            total_len: 0,
+            image_crc32: 0,
+            received_len: 0,
+            running_crc32: 0,
+            next_seq: 0,
+            header_scratch: [0; HEADER_SIZE as usize],
+        }
+    }
+
+    /// Once a transfer's image CRC32 has verified, atomically hands the
+    /// device over to the newly-written slot: patches the region table's
+    /// length and CRC32 entries for this slot to the transferred image (so
+    /// the loader's own verify-on-boot check passes) and flips `slot_state`
+    /// to make this slot active.
+    ///
+    /// `pending`/`confirmed` are set to the same "normal, trusted" values a
+    /// fresh build ships with (0/1, see xtask's `create_image`) rather than
+    /// the "pending and unconfirmed" combination -- per loader.S, that
+    /// combination makes the loader skip straight to the *other* slot
+    /// without ever attempting this one, which would mean the slot we just
+    /// finished verifying and writing is never actually booted. The loader's
+    /// own CRC check on the region table entry above remains the safety net:
+    /// if this slot is somehow bad despite passing the update's CRC, the
+    /// loader falls back to the previous slot automatically.
+    ///
+    /// The header sector holds the whole region table, so this is a read the
+    /// whole thing / patch the region entry and slot state / erase /
+    /// write-it-all-back, the same read-modify-write `stamp_config` does on
+    /// the image file.
+    fn commit_slot(&mut self) {
+        self.spi.read(0, &mut self.header_scratch);
+
+        let region_idx = self.region_id as usize;
+        let entry_off = 8 + region_idx * 12;
+        self.header_scratch[entry_off + 4..entry_off + 8]
+            .copy_from_slice(&self.total_len.to_le_bytes());
+        self.header_scratch[entry_off + 8..entry_off + 12]
+            .copy_from_slice(&self.image_crc32.to_le_bytes());
+
+        let slot = self.region_id - REGION_KERNEL_A; // 0 for slot A, 1 for slot B
+        let slot_off = SLOT_STATE_OFFSET as usize;
+        self.header_scratch[slot_off] = slot; // active_slot
+        self.header_scratch[slot_off + 1] = 0; // pending
+        self.header_scratch[slot_off + 2] = 1; // confirmed
+
+        self.spi.erase_sector(0);
+        self.spi.program(0, &self.header_scratch);
+    }
+
+    /// True once `ENTER_MAGIC` has armed a session and until it ends in
+    /// `Committed`/`Rejected`. `main`'s loop uses this to decide whether a
+    /// given byte belongs to this protocol or to the COBS one -- never both.
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, RxState::AwaitMagic { .. })
+    }
+
+    /// Feed one raw UART byte in; call from the main loop with the same byte
+    /// `uart::get_byte()` already returned. Returns `None` while disarmed or
+    /// while a frame is still being assembled, `Some(UpdateEvent::Progress)`
+    /// after each data frame (accepted or NAKed), and the terminal `Committed`/`Rejected`
+    /// once the image's CRC32 is known.
+    pub fn feed(&mut self, byte: u8) -> Option<UpdateEvent> {
+        match &mut self.state {
+            RxState::AwaitMagic { matched } => {
+                if byte == ENTER_MAGIC[*matched] {
+                    *matched += 1;
+                    if *matched == ENTER_MAGIC.len() {
+                        self.state = RxState::Idle;
+                    }
+                } else {
+                    // Mismatch: restart, but a byte that happens to be the
+                    // magic's first byte still counts as a 1-byte head start.
+                    *matched = (byte == ENTER_MAGIC[0]) as usize;
+                }
+                None
+            }
+            RxState::Idle => {
+                if byte == QUERY_SYNC {
+                    let mut active_slot = [0u8; 1];
+                    self.spi.read(SLOT_STATE_OFFSET, &mut active_slot);
+                    putc(active_slot[0]);
+                    return None;
+                }
+                self.state = match byte {
+                    HEADER_SYNC => RxState::Header { buf: [0; 9], pos: 0 },
+                    DATA_SYNC => RxState::DataHeader { buf: [0; 4], pos: 0 },
+                    _ => RxState::Idle, // resync: ignore stray bytes
+                };
+                None
+            }
+            RxState::Header { buf, pos } => {
+                buf[*pos] = byte;
+                *pos += 1;
+                if *pos == buf.len() {
+                    self.total_len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                    self.region_id = buf[4];
+                    self.image_crc32 = u32::from_le_bytes([buf[5], buf[6], buf[7], buf[8]]);
+                    self.received_len = 0;
+                    self.running_crc32 = 0;
+                    self.next_seq = 0;
+
+                    self.write_base = region_offset(self.region_id);
+                    if let Some(base) = self.write_base {
+                        // Erase up front so the whole image can be programmed
+                        // frame-by-frame as it arrives, never buffered whole
+                        // in RAM -- there isn't room for a full kernel image.
+                        let mut erase_offset = base;
+                        let end = base + self.total_len;
+                        while erase_offset < end {
+                            self.spi.erase_sector(erase_offset);
+                            erase_offset += SECTOR_SIZE as u32;
+                        }
+                    }
+                    send_ack();
+                    self.state = RxState::Idle;
+                }
+                None
+            }
+            RxState::DataHeader { buf, pos } => {
+                buf[*pos] = byte;
+                *pos += 1;
+                if *pos == buf.len() {
+                    let seq = u16::from_le_bytes([buf[0], buf[1]]);
+                    let len = u16::from_le_bytes([buf[2], buf[3]]) as usize;
+                    self.state = RxState::DataBody { seq, len, buf: [0; DATA_FRAME_SIZE], pos: 0 };
+                }
+                None
+            }
+            RxState::DataBody { seq, len, buf, pos } => {
+                buf[*pos] = byte;
+                *pos += 1;
+                if *pos == *len {
+                    self.state = RxState::DataCrc {
+                        seq: *seq,
+                        len: *len,
+                        buf: *buf,
+                        crc_buf: [0; 2],
+                        pos: 0,
+                    };
+                }
+                None
+            }
+            RxState::DataCrc { seq, len, buf, crc_buf, pos } => {
+                crc_buf[*pos] = byte;
+                *pos += 1;
+                if *pos < crc_buf.len() {
+                    return None;
+                }
+                let expected_crc = u16::from_le_bytes(*crc_buf);
+                let payload = &buf[..*len];
+                let frame_ok = crc16(payload) == expected_crc && *seq == self.next_seq;
+                if !frame_ok {
+                    send_nak(self.next_seq);
+                    self.state = RxState::Idle;
+                    return Some(UpdateEvent::Progress);
+                }
+
+                if let Some(base) = self.write_base {
+                    self.spi.program(base + self.received_len, payload);
+                }
+                self.running_crc32 = crc32_update(self.running_crc32, payload);
+                self.received_len += *len as u32;
+                self.next_seq += 1;
+                send_ack();
+                self.state = RxState::Idle;
+
+                if self.received_len >= self.total_len {
+                    // Transfer is over, one way or another -- disarm so the
+                    // COBS parser resumes seeing bytes; the host has to send
+                    // ENTER_MAGIC again to start another update.
+                    self.state = RxState::AwaitMagic { matched: 0 };
+                    if self.write_base.is_some() && self.running_crc32 == self.image_crc32 {
+                        self.commit_slot();
+                        send_ack();
+                        Some(UpdateEvent::Committed)
+                    } else {
+                        send_nak(0);
+                        Some(UpdateEvent::Rejected)
+                    }
+                } else {
+                    Some(UpdateEvent::Progress)
+                }
+            }
+        }
+    }
+
+    pub fn region_id(&self) -> u8 {
+        self.region_id
+    }
+}
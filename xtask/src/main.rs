@@ -1,4 +1,5 @@
 use std::{
+    convert::TryInto,
     env,
     io::{Read, Write},
     path::{Path, PathBuf},
@@ -13,6 +14,7 @@ const TARGET: &str = "riscv32imc-unknown-none-elf";
 const IMAGE_PATH: &'static str = formatcp!("target/{}/release/usbc_img.bin", TARGET);
 const DEST_FILE: &'static str = formatcp!("usbc_img.bin");
 const DESTDIR: &'static str = "code/precursors/";
+const KERNEL_BIN_PATH: &'static str = formatcp!("target/{}/release/kernel.bin", TARGET);
 
 fn main() {
     if let Err(e) = try_main() {
@@ -24,11 +26,16 @@ fn main() {
 fn try_main() -> Result<(), DynError> {
     let task = env::args().nth(1);
     match task.as_deref() {
-        Some("hw-image") => build_hw_image(false, env::args().nth(2))?,
+        Some("hw-image") => build_hw_image(false, false, env::args().nth(2))?,
+        Some("recovery-image") => build_hw_image(false, true, env::args().nth(2))?,
         Some("docs") => make_docs()?,
         Some("push") => push_to_pi(env::args().nth(2), env::args().nth(3))?,
         Some("stage-fw") => update_usb(true)?,
         Some("copy-precursors") => copy_precursors()?,
+        Some("stamp-config") => {
+            let serial = env::args().nth(2).ok_or("stamp-config requires a serial number")?;
+            stamp_config(Path::new(&IMAGE_PATH), &[format!("serial={}", serial)])?
+        }
         _ => print_help(),
     }
     Ok(())
@@ -38,10 +45,12 @@ fn print_help() {
     eprintln!(
         "Tasks:
 hw-image [soc.svd]      builds an image for real hardware
+recovery-image [soc.svd] builds a self-flashing recovery image, for RAM-loading via SWD/JTAG to un-brick a unit
 docs                    updates the documentation tree
 push  [ip] [id]         deploys files to burner Rpi. Example: push 192.168.1.2 ~/id_rsa. Assumes 'pi' as the user.
 stage-fw                stages the EC firmware and gateware for burn
 copy-precursors         copy precursors from a local build of the FPGA to the default location used by xtask
+stamp-config [serial]   stamps a unique serial into the built image's config region, without rebuilding
 "
     )
 }
@@ -104,30 +113,142 @@ fn push_to_pi(target: Option<String>, id: Option<String>) -> Result<(), DynError
     Ok(())
 }
 
-fn update_usb(do_ec: bool) -> Result<(), DynError> {
-    use std::process::Stdio;
-    use std::io::{BufRead, BufReader, Error, ErrorKind};
+// Frame format for the native updater, replacing the old usb_update.py shell-out.
+// One header frame, then fixed-size data frames; see sw/src/updater.rs for the
+// receiver side, which this must stay in lockstep with.
+const UPDATE_HEADER_SYNC: u8 = 0xAA;
+const UPDATE_DATA_SYNC: u8 = 0xBB;
+/// Asks the firmware which kernel slot is currently active, so the update
+/// that follows can target the *inactive* one. See sw/src/updater.rs's
+/// `QUERY_SYNC`.
+const UPDATE_QUERY_SYNC: u8 = 0xCC;
+const UPDATE_DATA_FRAME_SIZE: usize = 256;
+const UPDATE_ACK: u8 = 0x06;
+const UPDATE_NAK: u8 = 0x15;
+
+// Region ids for the two kernel slots, matching sw/src/updater.rs's
+// REGION_KERNEL_A/REGION_KERNEL_B and the region table order in create_image.
+const REGION_KERNEL_A: u8 = 2;
+const REGION_KERNEL_B: u8 = 3;
+
+/// Out-of-band handshake that arms the firmware's updater and, while armed,
+/// stops it feeding the same bytes to its COBS-framed command protocol.
+/// Must match sw/src/updater.rs's `ENTER_MAGIC` exactly.
+const UPDATE_ENTER_MAGIC: [u8; 4] = [0xA5, 0x5A, 0xC3, 0x3C];
+
+fn crc16(data: &[u8]) -> u16 {
+    let crc = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
+    crc.checksum(data)
+}
 
+fn update_usb(do_ec: bool) -> Result<(), DynError> {
     if do_ec {
-        println!("Staging EC objects");
-        let stdout = Command::new("python3")
-        .arg("tools/usb_update.py")
-        .arg("-e")
-        .arg("precursors/usbc_tester.bin")
-        .stdout(Stdio::piped())
-        .spawn()?
-        .stdout
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Could not capture output"))?;
-
-        let reader = BufReader::new(stdout);
-        reader.lines().for_each(|line|
-            println!("{}", line.unwrap())
-        );
+        println!("Staging kernel update");
+        send_kernel_update(KERNEL_BIN_PATH)?;
     }
 
     Ok(())
 }
 
+/// Opens the serial port the native updater protocol runs over and arms the
+/// firmware's updater with `UPDATE_ENTER_MAGIC`, so it starts consuming
+/// subsequent bytes instead of its COBS-framed command protocol. Shared by
+/// every `send_*` helper below so they all honor the same `USBC_UPDATE_PORT`
+/// override and never forget the handshake.
+fn open_update_port() -> Result<Box<dyn serialport::SerialPort>, DynError> {
+    let port_name = env::var("USBC_UPDATE_PORT").unwrap_or_else(|_| "/dev/ttyUSB0".to_string());
+    let mut port = serialport::new(&port_name, 115_200)
+        .timeout(std::time::Duration::from_millis(500))
+        .open()?;
+    port.write_all(&UPDATE_ENTER_MAGIC)?;
+    Ok(port)
+}
+
+/// Queries the firmware for its currently active kernel slot and sends the
+/// update to the *other* one, so a failed update always leaves the
+/// currently-running kernel untouched. See sw/src/updater.rs's `QUERY_SYNC`
+/// handling and `Updater::commit_slot`.
+fn send_kernel_update(path: &str) -> Result<(), DynError> {
+    let mut port = open_update_port()?;
+
+    port.write_all(&[UPDATE_QUERY_SYNC])?;
+    let mut active_slot = [0u8; 1];
+    port.read_exact(&mut active_slot)?;
+    let region_id = if active_slot[0] == 0 { REGION_KERNEL_B } else { REGION_KERNEL_A };
+    println!(
+        "active slot is {}; staging update into slot {}",
+        if active_slot[0] == 0 { "A" } else { "B" },
+        if region_id == REGION_KERNEL_A { "A" } else { "B" }
+    );
+
+    send_update_frames(port.as_mut(), path, region_id)
+}
+
+/// Drives the chunked, CRC-checked update protocol directly over the USB-serial
+/// link, replacing the old `tools/usb_update.py` shell-out. Host sends a header
+/// frame (total length, region id, whole-image CRC32), then fixed-size data
+/// frames each carrying a sequence number and a CRC16; the firmware ACKs each
+/// accepted frame and NAKs (with the sequence number it's expecting) on a CRC
+/// mismatch or reordering, so we just retry that one frame.
+fn send_update_frames(port: &mut dyn serialport::SerialPort, path: &str, region_id: u8) -> Result<(), DynError> {
+    let mut data = Vec::new();
+    std::fs::File::open(path)?.read_to_end(&mut data)?;
+    let image_crc = crc32(&data);
+
+    let mut header = Vec::with_capacity(10);
+    header.push(UPDATE_HEADER_SYNC);
+    header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    header.push(region_id);
+    header.extend_from_slice(&image_crc.to_le_bytes());
+    port.write_all(&header)?;
+    await_ack(port)?;
+
+    let total_frames = (data.len() + UPDATE_DATA_FRAME_SIZE - 1) / UPDATE_DATA_FRAME_SIZE;
+    for (seq, chunk) in data.chunks(UPDATE_DATA_FRAME_SIZE).enumerate() {
+        loop {
+            let mut frame = Vec::with_capacity(5 + chunk.len() + 2);
+            frame.push(UPDATE_DATA_SYNC);
+            frame.extend_from_slice(&(seq as u16).to_le_bytes());
+            frame.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            frame.extend_from_slice(chunk);
+            frame.extend_from_slice(&crc16(chunk).to_le_bytes());
+            port.write_all(&frame)?;
+
+            match await_ack(port) {
+                Ok(()) => break,
+                Err(e) => println!("frame {}/{} retry: {}", seq + 1, total_frames, e),
+            }
+        }
+        print!("\rsent frame {}/{}", seq + 1, total_frames);
+    }
+    println!();
+
+    // Firmware only commits the write once it has recomputed the whole-image
+    // CRC32 itself and it matches; otherwise the existing image is left alone.
+    let mut verdict = [0u8; 1];
+    port.read_exact(&mut verdict)?;
+    if verdict[0] == UPDATE_ACK {
+        println!("update verified and committed");
+        Ok(())
+    } else {
+        Err("firmware rejected the completed image (CRC mismatch)".into())
+    }
+}
+
+fn await_ack(port: &mut dyn serialport::SerialPort) -> Result<(), DynError> {
+    let mut reply = [0u8; 1];
+    port.read_exact(&mut reply)?;
+    match reply[0] {
+        UPDATE_ACK => Ok(()),
+        UPDATE_NAK => {
+            let mut seq_bytes = [0u8; 2];
+            port.read_exact(&mut seq_bytes)?;
+            Err(format!("NAK, firmware expects seq {}", u16::from_le_bytes(seq_bytes)).into())
+        }
+        _ => Err("garbled reply from firmware".into()),
+    }
+}
+
 fn copy_precursors() -> Result<(), DynError> {
     println!("copying csr.csv, soc.svd, and usbc_tester.bin from default build location to precursors/...");
     std::fs::copy("build/csr.csv", "precursors/csr.csv")?;
@@ -147,7 +268,7 @@ fn make_docs() -> Result<(), DynError> {
     Ok(())
 }
 
-fn build_hw_image(debug: bool, svd: Option<String>) -> Result<(), DynError> {
+fn build_hw_image(debug: bool, recovery: bool, svd: Option<String>) -> Result<(), DynError> {
     let svd_file = match svd {
         Some(s) => s,
         None => {println!("Using default soc.svd location of precursors/soc.svd"); "precursors/soc.svd".to_string() },
@@ -165,12 +286,19 @@ fn build_hw_image(debug: bool, svd: Option<String>) -> Result<(), DynError> {
 
     let loaderpath = PathBuf::from("sw/loader.S");
     let gatewarepath = PathBuf::from("precursors/usbc_tester.bin");
-    let output_bundle = create_image(&sw, &loaderpath, &gatewarepath)?;
+    let output_bundle = create_image(&sw, &loaderpath, &gatewarepath, recovery)?;
     println!();
-    println!(
-        "USBC tester software image bundle is available at {}",
-        output_bundle.display()
-    );
+    if recovery {
+        println!(
+            "RECOVERY image is available at {} -- load into RAM over SWD/JTAG, never flash this normally",
+            output_bundle.display()
+        );
+    } else {
+        println!(
+            "USBC tester software image bundle is available at {}",
+            output_bundle.display()
+        );
+    }
 
     Ok(())
 }
@@ -217,13 +345,93 @@ fn build(
     Ok(project_root().join(&format!("target/{}{}/{}", target_path, stream, project)))
 }
 
+// Header is a fixed, loader-readable size so the loader can find it without knowing
+// anything about the regions it describes in advance. One sector, so a rewrite of
+// the header alone never disturbs a neighboring region.
+const HEADER_SIZE: usize = 4096;
+// "UCTB" read back as a little-endian u32 -- easy to spot in a hex dump.
+const HEADER_MAGIC: u32 = u32::from_le_bytes(*b"UCTB");
+// Distinct magic for self-flashing recovery images. Keeping this separate from
+// HEADER_MAGIC means the loader can refuse to ever boot a recovery image from
+// flash -- it's only ever meant to be RAM-loaded via an external debugger --
+// and a recovery image can never be mistaken for, or accidentally re-flashed
+// as, a normal boot image.
+const RECOVERY_MAGIC: u32 = u32::from_le_bytes(*b"UCTR");
+const HEADER_VERSION: u32 = 1;
+
+/// One entry per flash region covered by the manifest. `crc32` is computed over
+/// exactly `length` bytes starting at `offset` (offsets are relative to the start
+/// of the image, i.e. they include `HEADER_SIZE`).
+#[derive(Clone, Copy)]
+struct RegionEntry {
+    offset: u32,
+    length: u32,
+    crc32: u32,
+}
+impl RegionEntry {
+    fn to_bytes(&self) -> [u8; 12] {
+        let mut b = [0u8; 12];
+        b[0..4].copy_from_slice(&self.offset.to_le_bytes());
+        b[4..8].copy_from_slice(&self.length.to_le_bytes());
+        b[8..12].copy_from_slice(&self.crc32.to_le_bytes());
+        b
+    }
+}
+
+/// Which kernel slot the loader should boot from. Mirrors the "active slot /
+/// pending / confirmed" state word of the Vorago flashloader: `pending` is set
+/// when a new slot has been staged but not yet proven to boot, and the loader
+/// (or the application, once it's up) is responsible for clearing it once the
+/// new kernel is known-good. A slot that is still `pending` on the next boot
+/// is assumed to be bad and the loader falls back to the other slot.
+#[derive(Clone, Copy)]
+struct SlotState {
+    active_slot: u8,
+    pending: u8,
+    confirmed: u8,
+}
+impl SlotState {
+    fn to_bytes(&self) -> [u8; 4] {
+        [self.active_slot, self.pending, self.confirmed, 0]
+    }
+}
+
+// Region table order is fixed: [gateware, loader, kernel slot A, kernel slot B, config].
+const NUM_REGIONS: usize = 5;
+// One sector, holding `key=value` lines (serial, mac, pass/fail thresholds).
+// Must match sw/src/config.rs's CONFIG_LEN.
+const CONFIG_REGION: usize = 4096;
+
+fn crc32(data: &[u8]) -> u32 {
+    let crc = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    crc.checksum(data)
+}
+
+/// Build the manifest header: magic, format version, a CRC32'd region table, and
+/// the A/B slot state word. Padded out to `HEADER_SIZE` with zeros. `magic` is
+/// `HEADER_MAGIC` for a normal boot image or `RECOVERY_MAGIC` for a self-flashing
+/// recovery image.
+fn build_header(magic: u32, regions: &[RegionEntry; NUM_REGIONS], slot_state: SlotState) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(&magic.to_le_bytes());
+    header.extend_from_slice(&HEADER_VERSION.to_le_bytes());
+    for region in regions.iter() {
+        header.extend_from_slice(&region.to_bytes());
+    }
+    header.extend_from_slice(&slot_state.to_bytes());
+    assert!(header.len() <= HEADER_SIZE, "manifest header grew past its reserved sector");
+    header.resize(HEADER_SIZE, 0);
+    header
+}
+
 fn create_image(
     kernel: &Path,
     loader: &PathBuf,
     gateware: &PathBuf,
+    recovery: bool,
 ) -> Result<PathBuf, DynError> {
     let loader_bin_path = &format!("target/{}/release/loader.bin", TARGET);
-    let kernel_bin_path = &format!("target/{}/release/kernel.bin", TARGET);
+    let kernel_bin_path = KERNEL_BIN_PATH;
     // kernel region limit primarily set by the loader copy bytes. Can be grown, at expense of heap.
     const KERNEL_REGION: usize = 76 * 1024;
     // this is defined by size of UP5k bitstream plus rounding to sector erase size of 4k; reset vector points just beyond this
@@ -266,7 +474,7 @@ fn create_image(
     Command::new("riscv64-unknown-elf-objcopy")
     .arg("-O").arg("binary")
     .arg(kernel)
-    .arg(PathBuf::from(&kernel_bin_path))
+    .arg(PathBuf::from(kernel_bin_path))
     .output()
     .expect("Failed to copy the kernel binary");
 
@@ -276,7 +484,7 @@ fn create_image(
     let mut kernel_bin: [u8; KERNEL_REGION] = [0; KERNEL_REGION];
 
     std::fs::File::open(gateware)?.read(&mut gateware_bin)?;
-    let kernel_bytes = std::fs::File::open(PathBuf::from(&kernel_bin_path))?.read(&mut kernel_bin);
+    let kernel_bytes = std::fs::File::open(PathBuf::from(kernel_bin_path))?.read(&mut kernel_bin);
     match kernel_bytes {
         Ok(bytes) => {
             println!("Read {} kernel bytes into image.", bytes);
@@ -289,14 +497,90 @@ fn create_image(
         }
     }
 
+    // Fresh builds stamp the same kernel into both slots so the loader boots
+    // correctly no matter which slot it picks first; field updates (push_to_pi /
+    // stage-fw) are what actually differentiate slot A from slot B later.
+    let gateware_offset = HEADER_SIZE as u32;
+    let loader_offset = gateware_offset + GATEWARE_REGION as u32;
+    let kernel_a_offset = loader_offset + loader.len() as u32;
+    let kernel_b_offset = kernel_a_offset + KERNEL_REGION as u32;
+    let config_offset = kernel_b_offset + KERNEL_REGION as u32;
+
+    // Blank (erased) until `stamp_config` personalizes a built image; the
+    // firmware's config parser treats an all-zero region as "use defaults".
+    let config_bin = vec![0u8; CONFIG_REGION];
+
+    let regions = [
+        RegionEntry { offset: gateware_offset, length: GATEWARE_REGION as u32, crc32: crc32(&gateware_bin) },
+        RegionEntry { offset: loader_offset, length: loader.len() as u32, crc32: crc32(&loader) },
+        RegionEntry { offset: kernel_a_offset, length: KERNEL_REGION as u32, crc32: crc32(&kernel_bin) },
+        RegionEntry { offset: kernel_b_offset, length: KERNEL_REGION as u32, crc32: crc32(&kernel_bin) },
+        RegionEntry { offset: config_offset, length: CONFIG_REGION as u32, crc32: crc32(&config_bin) },
+    ];
+    let slot_state = SlotState { active_slot: 0, pending: 0, confirmed: 1 };
+    let magic = if recovery { RECOVERY_MAGIC } else { HEADER_MAGIC };
+    let header = build_header(magic, &regions, slot_state);
+
     let mut image = std::fs::File::create(PathBuf::from(&IMAGE_PATH))?;
+    image.write(&header)?;
     image.write(&gateware_bin)?;
     image.write(&loader)?;
-    image.write(&kernel_bin)?;
+    image.write(&kernel_bin)?; // slot A
+    image.write(&kernel_bin)?; // slot B (same payload until the first field update)
+    image.write(&config_bin)?;
+
+    println!(
+        "Wrote manifest: magic {:#x}, version {}, active slot {}{}",
+        magic,
+        HEADER_VERSION,
+        slot_state.active_slot,
+        if recovery { " [RECOVERY]" } else { "" },
+    );
 
     Ok(project_root().join(&IMAGE_PATH))
 }
 
+/// Stamps the config region (index 4) of an already-built image with
+/// `key=value` lines -- at minimum a unique `serial` -- without rebuilding the
+/// firmware. Patches just that region and its CRC32 in place, so `push_to_pi`
+/// can personalize each burned unit from the same build output.
+fn stamp_config(image_path: &Path, lines: &[String]) -> Result<(), DynError> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = std::fs::OpenOptions::new().read(true).write(true).open(image_path)?;
+    let mut header = vec![0u8; HEADER_SIZE];
+    file.read_exact(&mut header)?;
+
+    // Region entries start at offset 8 (after magic + version); each is 12 bytes.
+    const CONFIG_REGION_INDEX: usize = 4;
+    let entry_off = 8 + CONFIG_REGION_INDEX * 12;
+    let region_offset = u32::from_le_bytes(header[entry_off..entry_off + 4].try_into().unwrap());
+    let region_len = u32::from_le_bytes(header[entry_off + 4..entry_off + 8].try_into().unwrap()) as usize;
+
+    let mut config_bin = vec![0u8; region_len];
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(line);
+        text.push('\n');
+    }
+    let text_bytes = text.as_bytes();
+    if text_bytes.len() > region_len {
+        return Err("config text too large for the reserved config region".into());
+    }
+    config_bin[..text_bytes.len()].copy_from_slice(text_bytes);
+
+    let new_crc = crc32(&config_bin);
+    header[entry_off + 8..entry_off + 12].copy_from_slice(&new_crc.to_le_bytes());
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.seek(SeekFrom::Start(region_offset as u64))?;
+    file.write_all(&config_bin)?;
+
+    println!("Stamped config region ({} bytes) with {} line(s)", region_len, lines.len());
+    Ok(())
+}
+
 fn cargo() -> String {
     env::var("CARGO").unwrap_or_else(|_| "cargo".to_string())
 }